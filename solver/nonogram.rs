@@ -1,6 +1,6 @@
 use ahash::AHasher;
 use assumption::Assumption;
-use common::{CellValue, LineHints, Unknown, KNOWN};
+use common::{CellValue, ColorId, LineHints};
 use field::Field;
 use itertools::Itertools;
 use line::{Line, LineCache, LineType};
@@ -14,6 +14,7 @@ use LineType::*;
 use InternalSolution::*;
 
 mod assumption;
+mod bitset;
 mod common;
 mod field;
 mod line;
@@ -33,10 +34,33 @@ enum InternalSolution {
 
 type ABuildHasher = BuildHasherDefault<AHasher>;
 
+/// A hint entry as it appears in JSON: either a bare run length (a classic
+/// two-color puzzle, implicitly the sole foreground color) or a
+/// `[length, color]` pair for a colored puzzle.
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum HintEntry {
+    Plain(usize),
+    Colored(usize, ColorId),
+}
+
+impl From<HintEntry> for (usize, ColorId) {
+    fn from(entry: HintEntry) -> Self {
+        match entry {
+            HintEntry::Plain(len) => (len, 1),
+            HintEntry::Colored(len, color) => (len, color),
+        }
+    }
+}
+
 #[derive(serde::Deserialize)]
 struct NonoDescription {
-    row_hints: Vec<LineHints>,
-    col_hints: Vec<LineHints>,
+    row_hints: Vec<Vec<HintEntry>>,
+    col_hints: Vec<Vec<HintEntry>>,
+    /// Names of the puzzle's colors, 1-indexed (color 0 is always the
+    /// background). Absent or empty means a classic two-color puzzle.
+    #[serde(default)]
+    palette: Vec<String>,
 }
 
 pub struct Solver {
@@ -46,6 +70,7 @@ pub struct Solver {
     col_cache: Vec<LineCache<ABuildHasher>>,
     max_depth: usize,
     find_all: bool,
+    palette_size: u32,
     pub solutions: RefCell<HashMap<Vec<CellValue>, Field>>,
 }
 
@@ -56,11 +81,16 @@ impl Solver {
         find_all: bool,
     ) -> Result<Self, serde_json::Error> {
         let descr: NonoDescription = serde_json::from_reader(rdr)?;
+        let palette_size = descr.palette.len().max(1) as u32;
+        let to_hints = |lines: Vec<Vec<HintEntry>>| -> Vec<LineHints> {
+            lines.into_iter().map(|line| line.into_iter().map(Into::into).collect()).collect()
+        };
         Ok(Self::from_hints(
-            descr.row_hints,
-            descr.col_hints,
+            to_hints(descr.row_hints),
+            to_hints(descr.col_hints),
             max_depth,
             find_all,
+            palette_size,
         ))
     }
 
@@ -69,6 +99,7 @@ impl Solver {
         col_hints: Vec<LineHints>,
         max_depth: usize,
         find_all: bool,
+        palette_size: u32,
     ) -> Self {
         let row_cache = (0..row_hints.len())
             .map(|_| RefCell::new(HashMap::default()))
@@ -76,11 +107,20 @@ impl Solver {
         let col_cache = (0..col_hints.len())
             .map(|_| RefCell::new(HashMap::default()))
             .collect();
-        Self { row_hints, col_hints, row_cache, col_cache, max_depth, find_all, solutions: RefCell::new(HashMap::new()) }
+        Self {
+            row_hints,
+            col_hints,
+            row_cache,
+            col_cache,
+            max_depth,
+            find_all,
+            palette_size,
+            solutions: RefCell::new(HashMap::new()),
+        }
     }
 
     pub fn create_field(&self) -> Field {
-        Field::new(self.nrows(), self.ncols())
+        Field::new(self.nrows(), self.ncols(), self.palette_size)
     }
 
     fn nrows(&self) -> usize {
@@ -92,11 +132,11 @@ impl Solver {
     }
 
     fn row_line<'a>(&'a self, field: &'a Field, row_idx: usize) -> Line {
-        Line::new(Row, row_idx, &self.row_hints[row_idx], field.row(row_idx))
+        Line::new(Row, row_idx, &self.row_hints[row_idx], field.row(row_idx), self.palette_size)
     }
 
     fn col_line<'a>(&'a self, field: &'a Field, col_idx: usize) -> Line {
-        Line::new(Col, col_idx, &self.col_hints[col_idx], field.col(col_idx))
+        Line::new(Col, col_idx, &self.col_hints[col_idx], field.col(col_idx), self.palette_size)
     }
 
     fn line<'a>(&'a self, field: &'a Field, line_type: LineType, line_idx: usize) -> Line {
@@ -122,7 +162,8 @@ impl Solver {
         let mut all_changes: Vec<Assumption> = Vec::new();
         for line_idx in line_changes.iter().enumerate().filter_map(|(idx, &val)| if val > 0 { Some(idx) } else { None }) {
             let mut line = self.line(&field, line_type, line_idx);
-            match line.solve(self.cache(line_type, line_idx)).as_ref() {
+            let result = line.solve(self.cache(line_type, line_idx));
+            match result.solution.as_ref() {
                 Some(changes) if !changes.is_empty() => {
                     apply_changes(changes, field.to_mut(), &mut all_changes);
                 }
@@ -176,37 +217,60 @@ impl Solver {
         let mut changed_rows = vec![0u8; self.nrows()];
         let mut changed_cols = vec![0u8; self.ncols()];
         for coords in self.iter_coords() {
-            if field.get(coords) != Unknown {
+            let candidates = field.get(coords);
+            if candidates.is_known() {
                 continue;
             }
-            let mut has_controversy = false;
-            for val in KNOWN {
-                let ass = Assumption { coords, val };
+            let mut impossible = CellValue::none();
+            for color in 0..=self.palette_size {
+                if !candidates.can_be(color) {
+                    continue;
+                }
+                let ass = Assumption::Is { coords, color };
                 ass.apply(&mut field);
-                changed_rows[ass.coords.0] += 1;
-                changed_cols[ass.coords.1] += 1;
+                changed_rows[coords.0] += 1;
+                changed_cols[coords.1] += 1;
                 match self.do_solve(&field, max_depth, &changed_rows, &changed_cols) {
                     Solved => {
                         if !self.find_all {
                             return Solved;
                         }
                         ass.unapply(&mut field);
-                        changed_rows[ass.coords.0] -= 1;
-                        changed_cols[ass.coords.1] -= 1;
+                        changed_rows[coords.0] -= 1;
+                        changed_cols[coords.1] -= 1;
                     }
                     Unsolved(_) => {
                         has_unsolved = true;
                         ass.unapply(&mut field);
-                        changed_rows[ass.coords.0] -= 1;
-                        changed_cols[ass.coords.1] -= 1;
+                        changed_rows[coords.0] -= 1;
+                        changed_cols[coords.1] -= 1;
                     }
                     Controversial => {
-                        if has_controversy {
-                            return Controversial;
-                        }
-                        ass.invert().apply(&mut field);
-                        all_changes.push(ass.invert());
-                        has_controversy = true;
+                        impossible = impossible.add_color(color);
+                        ass.unapply(&mut field);
+                        changed_rows[coords.0] -= 1;
+                        changed_cols[coords.1] -= 1;
+                    }
+                }
+            }
+            let narrowed = candidates.without_all(impossible);
+            if narrowed.is_contradiction() {
+                return Controversial;
+            }
+            if let Some(color) = narrowed.color() {
+                let ass = Assumption::Is { coords, color };
+                ass.apply(&mut field);
+                changed_rows[coords.0] += 1;
+                changed_cols[coords.1] += 1;
+                all_changes.push(ass);
+            } else {
+                for color in 0..=self.palette_size {
+                    if candidates.can_be(color) && !narrowed.can_be(color) {
+                        let ass = Assumption::IsNot { coords, color };
+                        ass.apply(&mut field);
+                        changed_rows[coords.0] += 1;
+                        changed_cols[coords.1] += 1;
+                        all_changes.push(ass);
                     }
                 }
             }
@@ -246,8 +310,8 @@ impl Solver {
                         if !changes.is_empty() {
                             apply_changes(&changes, &mut field, &mut all_changes);
                             for ass in changes {
-                                changed_rows.to_mut()[ass.coords.0] += 1;
-                                changed_cols.to_mut()[ass.coords.1] += 1;
+                                changed_rows.to_mut()[ass.coords().0] += 1;
+                                changed_cols.to_mut()[ass.coords().1] += 1;
                             }
                             continue 'outer;
                         }
@@ -300,13 +364,18 @@ mod tests {
         }
     }
 
+    fn bw(lens: Vec<usize>) -> LineHints {
+        lens.into_iter().map(|len| (len, 1)).collect()
+    }
+
     #[test]
     fn solve_by_line() {
         let solver = Solver::from_hints(
-            vec![vec![5], vec![1], vec![5], vec![1], vec![5]],
-            vec![vec![3, 1], vec![1, 1, 1], vec![1, 1, 1], vec![1, 1, 1], vec![1, 3]],
+            vec![bw(vec![5]), bw(vec![1]), bw(vec![5]), bw(vec![1]), bw(vec![5])],
+            vec![bw(vec![3, 1]), bw(vec![1, 1, 1]), bw(vec![1, 1, 1]), bw(vec![1, 1, 1]), bw(vec![1, 3])],
             0,
             false,
+            1,
         );
         solver.solve().assert_solved(&["\
                 #####\n\
@@ -319,7 +388,7 @@ mod tests {
 
     #[test]
     fn solve_ambiguous() {
-        let solver = Solver::from_hints(vec![vec![1], vec![1]], vec![vec![1], vec![1]], 3, true);
+        let solver = Solver::from_hints(vec![bw(vec![1]), bw(vec![1])], vec![bw(vec![1]), bw(vec![1])], 3, true, 1);
         solver.solve().assert_solved(&[
             "#.\n\
              .#\n",
@@ -332,10 +401,11 @@ mod tests {
     #[test]
     fn solve_double_ambiguous_naive() {
         let solver = Solver::from_hints(
-            vec![vec![1, 1], vec![1, 1]],
-            vec![vec![1], vec![1], vec![], vec![1], vec![1]],
+            vec![bw(vec![1, 1]), bw(vec![1, 1])],
+            vec![bw(vec![1]), bw(vec![1]), bw(vec![]), bw(vec![1]), bw(vec![1])],
             2,
             true,
+            1,
         );
         solver.solve().assert_solved(&[
             "#..#.\n\
@@ -1,30 +1,48 @@
-use super::common::{CellValue, Unknown};
+use super::common::{CellValue, ColorId};
 use super::line::LineType;
 use super::Field;
 
-#[derive(Debug, Default, Hash, Eq, PartialEq, Clone)]
-pub struct Assumption {
-    pub coords: (usize, usize),
-    pub val: CellValue,
+/// A single-cell deduction: either a commitment ("this cell is exactly
+/// `color`") or an exclusion ("this cell cannot be `color`"). The two are
+/// each other's inverse, generalizing the old binary Filled/Empty flip to an
+/// arbitrary palette.
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Copy)]
+pub enum Assumption {
+    Is { coords: (usize, usize), color: ColorId },
+    IsNot { coords: (usize, usize), color: ColorId },
 }
 
 impl Assumption {
+    pub fn coords(&self) -> (usize, usize) {
+        match *self {
+            Assumption::Is { coords, .. } => coords,
+            Assumption::IsNot { coords, .. } => coords,
+        }
+    }
+
     pub fn invert(&self) -> Self {
-        Self { coords: self.coords, val: self.val.invert() }
+        match *self {
+            Assumption::Is { coords, color } => Assumption::IsNot { coords, color },
+            Assumption::IsNot { coords, color } => Assumption::Is { coords, color },
+        }
     }
 
     pub fn apply(&self, field: &mut Field) {
-        field.set(self.coords, self.val);
+        match *self {
+            Assumption::Is { coords, color } => field.set(coords, CellValue::single(color)),
+            Assumption::IsNot { coords, color } => field.set(coords, field.get(coords).without(color)),
+        }
     }
 
     pub fn unapply(&self, field: &mut Field) {
-        field.set(self.coords, Unknown);
+        field.set(self.coords(), field.undefined_value());
     }
 
     pub fn line_idx(&self, line_type: LineType) -> usize {
+        let coords = self.coords();
         match line_type {
-            LineType::Row => self.coords.0,
-            LineType::Col => self.coords.1,
+            LineType::Row => coords.0,
+            LineType::Col => coords.1,
         }
     }
 }
@@ -0,0 +1,48 @@
+/// A fixed-length bit vector packed into `u64` words, so a whole line's
+/// worth of bits can be counted or compared word-at-a-time instead of
+/// cell-at-a-time.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct BitLine {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl BitLine {
+    pub fn new(len: usize, all_set: bool) -> Self {
+        let nwords = (len + 63) / 64;
+        let mut words = vec![if all_set { u64::MAX } else { 0 }; nwords];
+        Self::mask_tail(&mut words, len);
+        Self { words, len }
+    }
+
+    fn mask_tail(words: &mut [u64], len: usize) {
+        let rem = len % 64;
+        if rem != 0 {
+            if let Some(last) = words.last_mut() {
+                *last &= (1u64 << rem) - 1;
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn get(&self, idx: usize) -> bool {
+        (self.words[idx / 64] >> (idx % 64)) & 1 != 0
+    }
+
+    pub fn set(&mut self, idx: usize, val: bool) {
+        let bit = 1u64 << (idx % 64);
+        let word = &mut self.words[idx / 64];
+        if val {
+            *word |= bit;
+        } else {
+            *word &= !bit;
+        }
+    }
+
+    pub fn count_ones(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+}
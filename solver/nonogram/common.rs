@@ -0,0 +1,94 @@
+pub type ColorId = u32;
+
+/// The background ("empty") color is always color 0.
+pub const BACKGROUND: ColorId = 0;
+
+/// A cell's set of still-possible colors, as a bitmask: bit 0 is the
+/// background color, bits `1..=palette_size` are the puzzle's foreground
+/// colors. A solved cell has exactly one bit set; the starting "nothing
+/// known yet" value has every candidate bit set.
+#[derive(Debug, Hash, Eq, PartialEq, Copy, Clone)]
+pub struct CellValue(u64);
+
+/// Each hint is a run of `length` cells of `color`.
+pub type LineHints = Vec<(usize, ColorId)>;
+
+impl CellValue {
+    pub fn single(color: ColorId) -> Self {
+        Self(1 << color)
+    }
+
+    pub fn none() -> Self {
+        Self(0)
+    }
+
+    pub fn undefined(palette_size: u32) -> Self {
+        Self((1 << (palette_size + 1)) - 1)
+    }
+
+    pub fn is_known(&self) -> bool {
+        self.0.count_ones() == 1
+    }
+
+    pub fn is_contradiction(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn color(&self) -> Option<ColorId> {
+        self.is_known().then(|| self.0.trailing_zeros())
+    }
+
+    pub fn can_be(&self, color: ColorId) -> bool {
+        self.0 & (1 << color) != 0
+    }
+
+    pub fn can_be_blank(&self) -> bool {
+        self.can_be(BACKGROUND)
+    }
+
+    /// The colors this cell could still take, least significant bit first.
+    pub fn colors(&self) -> impl Iterator<Item = ColorId> {
+        let mut bits = self.0;
+        std::iter::from_fn(move || {
+            if bits == 0 {
+                None
+            } else {
+                let color = bits.trailing_zeros();
+                bits &= bits - 1;
+                Some(color)
+            }
+        })
+    }
+
+    /// Merges `color` into this cell's candidate set; nonogrid calls this
+    /// `add_color`.
+    pub fn add_color(&self, color: ColorId) -> Self {
+        Self(self.0 | (1 << color))
+    }
+
+    pub fn intersect(&self, other: CellValue) -> CellValue {
+        Self(self.0 & other.0)
+    }
+
+    pub fn without(&self, color: ColorId) -> CellValue {
+        Self(self.0 & !(1 << color))
+    }
+
+    pub fn without_all(&self, other: CellValue) -> CellValue {
+        Self(self.0 & !other.0)
+    }
+}
+
+/// Renders a solved (or partially solved) line: background as `.`, the sole
+/// foreground color of a classic puzzle as `#`, any other color as a
+/// base-36 digit, and a still-undetermined cell as `~`.
+pub fn line_to_str(line: &[CellValue]) -> String {
+    line.iter()
+        .map(|cell| match cell.color() {
+            Some(BACKGROUND) => '.',
+            Some(1) => '#',
+            Some(c) => std::char::from_digit(c, 36).unwrap_or('?'),
+            None => '~',
+        })
+        .collect()
+}
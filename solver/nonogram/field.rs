@@ -1,10 +1,11 @@
-use super::common::{line_to_str, CellValue, Unknown};
+use super::common::{line_to_str, CellValue};
 use std::fmt::Display;
 
 #[derive(Clone, Eq, PartialEq, Hash, Debug)]
 pub struct Field {
     nrows: usize,
     ncols: usize,
+    palette_size: u32,
     rows: Vec<CellValue>,
     cols: Vec<CellValue>,
 }
@@ -20,17 +21,25 @@ impl Display for Field {
 }
 
 impl Field {
-    pub fn new(nrows: usize, ncols: usize) -> Self {
+    pub fn new(nrows: usize, ncols: usize, palette_size: u32) -> Self {
+        let undefined = CellValue::undefined(palette_size);
         Self {
             nrows,
             ncols,
-            rows: vec![Unknown; nrows * ncols],
-            cols: vec![Unknown; nrows * ncols],
+            palette_size,
+            rows: vec![undefined; nrows * ncols],
+            cols: vec![undefined; nrows * ncols],
         }
     }
 
+    /// The cell value standing for "no color ruled out yet", used to reset a
+    /// cell when an `Assumption` is unapplied.
+    pub fn undefined_value(&self) -> CellValue {
+        CellValue::undefined(self.palette_size)
+    }
+
     pub fn is_solved(&self) -> bool {
-        self.rows.iter().all(|&x| x != Unknown)
+        self.rows.iter().all(CellValue::is_known)
     }
 
     pub fn row(&self, idx: usize) -> &[CellValue] {
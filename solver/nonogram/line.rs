@@ -1,20 +1,68 @@
 use super::assumption::Assumption;
-use super::common::{line_to_str, CellValue, LineHints};
-use crate::nonogram::common::KNOWN;
+use super::bitset::BitLine;
+use super::common::{line_to_str, CellValue, ColorId, LineHints, BACKGROUND};
 use std::borrow::Cow;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::hash::BuildHasher;
 use std::rc::Rc;
-use CellValue::*;
 use LineType::*;
 
 #[cfg(test)]
 mod tests;
 
-pub type LineCache<S> = RefCell<HashMap<Vec<CellValue>, LineSolution, S>>;
+pub type LineCache<S> = RefCell<HashMap<PackedLine, LineSolution, S>>;
 pub type LineSolution = Rc<Option<Vec<Assumption>>>;
 
+/// The outcome of a `Line::solve` call, together with the scheduling signals
+/// a grid driver needs to prioritize which line to visit next: `resolved` is
+/// how many cells this call just pinned down to a single color, and `rate`
+/// is the fraction of the line now known, counting those. A driver juggling
+/// a priority queue of dirty lines can favor whichever is closest to done,
+/// or whichever just produced the most deductions.
+#[derive(Clone, Debug)]
+pub struct LineSolveResult {
+    pub solution: LineSolution,
+    pub resolved: usize,
+    pub rate: f64,
+}
+
+/// A line's cell states packed into one [`BitLine`] per color: bit `i` of
+/// plane `c` is set iff cell `i` can still be color `c`. A handful of machine
+/// words stand in for a heap-allocated `Vec<CellValue>` as a `HashMap` key,
+/// and since the packing only depends on cell content, a row and a column
+/// left in the same state hash and compare equal.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct PackedLine {
+    planes: Vec<BitLine>,
+}
+
+impl PackedLine {
+    fn pack(cells: &[CellValue], palette_size: u32) -> Self {
+        let n = cells.len();
+        let mut planes: Vec<BitLine> = (0..=palette_size).map(|_| BitLine::new(n, false)).collect();
+        for (idx, cell) in cells.iter().enumerate() {
+            for color in cell.colors() {
+                planes[color as usize].set(idx, true);
+            }
+        }
+        debug_assert!(planes.iter().all(|plane| plane.len() == n));
+        Self { planes }
+    }
+
+    /// A necessary (not sufficient) feasibility check over whole words: no
+    /// color can be forced into more cells than currently allow it. Lets
+    /// `do_solve` reject an obviously-broken line via a few `count_ones`
+    /// calls instead of walking the forward/backward automaton first.
+    fn is_clearly_infeasible(&self, hints: &LineHints) -> bool {
+        let mut needed = vec![0usize; self.planes.len()];
+        for &(len, color) in hints {
+            needed[color as usize] += len;
+        }
+        needed.iter().zip(self.planes.iter()).any(|(&need, plane)| plane.count_ones() < need)
+    }
+}
+
 #[derive(Hash, Eq, PartialEq, Copy, Clone, Debug)]
 pub enum LineType {
     Row,
@@ -35,11 +83,18 @@ pub struct Line<'a> {
     line_idx: usize,
     hints: &'a LineHints,
     cells: Cow<'a, [CellValue]>,
+    palette_size: u32,
 }
 
 impl<'a> Line<'a> {
-    pub fn new(line_type: LineType, line_idx: usize, hints: &'a LineHints, cells: &'a [CellValue]) -> Self {
-        Self { line_type, line_idx, hints, cells: Cow::from(cells) }
+    pub fn new(
+        line_type: LineType,
+        line_idx: usize,
+        hints: &'a LineHints,
+        cells: &'a [CellValue],
+        palette_size: u32,
+    ) -> Self {
+        Self { line_type, line_idx, hints, cells: Cow::from(cells), palette_size }
     }
 
     #[allow(dead_code)]
@@ -47,107 +102,197 @@ impl<'a> Line<'a> {
         line_to_str(&self.cells)
     }
 
-    fn do_verify(&self, hint_idx: usize, cells_offset: usize, last_filled: Option<usize>) -> bool {
-        if cells_offset >= self.cells.len() {
-            return hint_idx == self.hints.len();
+    fn get_coords(&self, idx: usize) -> (usize, usize) {
+        match self.line_type {
+            Row => (self.line_idx, idx),
+            Col => (idx, self.line_idx),
         }
-        let cells = &self.cells[cells_offset..];
-        if hint_idx == self.hints.len() {
-            return last_filled.map_or(true, |lf| cells_offset > lf);
+    }
+
+    /// Deduces every cell the hints force, via a single forward/backward
+    /// automaton pass instead of the old per-cell probe-and-backtrack.
+    ///
+    /// States `0..=k` mean "hints `0..j` fully placed". `fwd[p][j]` is
+    /// whether that state is reachable having consumed only `cells[0..p]`;
+    /// `bwd[p][j]` is whether, starting idle in state `j` at `p`, the
+    /// remaining hints `j..k` can still be placed in `cells[p..n]`. Runs of
+    /// different colors may sit directly adjacent (no separator needed,
+    /// since the color change alone marks the boundary); same-colored runs
+    /// still require a background cell between them, same as the classic
+    /// monochrome case.
+    fn do_solve(&mut self) -> Option<Vec<Assumption>> {
+        let n = self.cells.len();
+        let k = self.hints.len();
+
+        if PackedLine::pack(&self.cells, self.palette_size).is_clearly_infeasible(self.hints) {
+            return None;
         }
-        let current_hint = self.hints[hint_idx];
-        let size = cells.len();
 
-        if current_hint > size {
-            return false;
+        // For each color used by some hint, the nearest index >= p whose
+        // cell can't take that color, or `n` if none; turns "every cell in
+        // this run can take this run's color" into an O(1) check.
+        let mut next_blocked: HashMap<ColorId, Vec<usize>> = HashMap::new();
+        for &(_, color) in self.hints.iter() {
+            next_blocked.entry(color).or_insert_with(|| {
+                let mut blocked = vec![n; n + 1];
+                for p in (0..n).rev() {
+                    blocked[p] = if self.cells[p].can_be(color) { blocked[p + 1] } else { p };
+                }
+                blocked
+            });
         }
-        for (start, &val) in cells[..size - current_hint + 1].iter().enumerate() {
-            let end = start + current_hint;
-            if cells[start..end].iter().all(|&x| x != Empty)
-                && (end == size || cells[end] != Filled)
-                && self.do_verify(hint_idx + 1, cells_offset + end + 1, last_filled)
-            {
-                return true;
-            }
-            if val == Filled {
-                return false;
+        let run_fits = |p: usize, len: usize, color: ColorId| p + len <= n && next_blocked[&color][p] >= p + len;
+        // Whether hint `j`'s run needs a mandatory background separator
+        // before hint `j + 1` can start: only required when the next run is
+        // the same color, since two same-colored runs touching would look
+        // like one longer run.
+        let needs_gap = |j: usize, color: ColorId| j + 1 < k && self.hints[j + 1].1 == color;
+
+        let mut fwd = vec![vec![false; k + 1]; n + 1];
+        fwd[0][0] = true;
+        for p in 0..n {
+            for j in 0..=k {
+                if !fwd[p][j] {
+                    continue;
+                }
+                if self.cells[p].can_be(BACKGROUND) {
+                    fwd[p + 1][j] = true;
+                }
+                if j < k {
+                    let (len, color) = self.hints[j];
+                    if run_fits(p, len, color) {
+                        if p + len == n {
+                            fwd[n][j + 1] = true;
+                        } else if needs_gap(j, color) {
+                            if self.cells[p + len].can_be(BACKGROUND) {
+                                fwd[p + len + 1][j + 1] = true;
+                            }
+                        } else {
+                            fwd[p + len][j + 1] = true;
+                        }
+                    }
+                }
             }
         }
-        false
-    }
-
-    fn verify(&self, last_filled: Option<usize>) -> bool {
-        self.do_verify(0, 0, last_filled)
-    }
+        if !fwd[n][k] {
+            return None;
+        }
 
-    fn get_coords(&self, idx: usize) -> (usize, usize) {
-        match self.line_type {
-            Row => (self.line_idx, idx),
-            Col => (idx, self.line_idx),
+        let mut bwd = vec![vec![false; k + 1]; n + 1];
+        bwd[n][k] = true;
+        for p in (0..n).rev() {
+            for j in 0..=k {
+                let via_skip = self.cells[p].can_be(BACKGROUND) && bwd[p + 1][j];
+                let via_run = j < k && {
+                    let (len, color) = self.hints[j];
+                    run_fits(p, len, color)
+                        && if p + len == n {
+                            j + 1 == k
+                        } else if needs_gap(j, color) {
+                            self.cells[p + len].can_be(BACKGROUND) && bwd[p + len + 1][j + 1]
+                        } else {
+                            bwd[p + len][j + 1]
+                        }
+                };
+                bwd[p][j] = via_skip || via_run;
+            }
         }
-    }
 
-    fn get_last_filled(&self) -> Option<usize> {
-        self.cells
-            .iter()
-            .enumerate()
-            .filter(|(_, &v)| v == Filled)
-            .map(|(idx, _)| idx)
-            .last()
-    }
+        let mut can_empty = vec![false; n];
+        for (idx, can) in can_empty.iter_mut().enumerate() {
+            *can = self.cells[idx].can_be(BACKGROUND) && (0..=k).any(|j| fwd[idx][j] && bwd[idx + 1][j]);
+        }
 
-    fn do_solve(&mut self) -> Option<Vec<Assumption>> {
-        let mut last_filled = self.get_last_filled();
-        if !self.verify(last_filled) {
-            return None;
+        // For each hint, mark which cells some valid run of it could cover,
+        // by color. `starts[p]` says whether beginning that run at `p` both
+        // leaves the automaton (via `fwd`) able to reach `p` and the tail
+        // (via `bwd`) able to finish afterwards; a prefix sum over `starts`
+        // turns "does any start in the window covering cell `idx` work" into
+        // an O(1) range-sum lookup, keeping the whole pass O(n*k).
+        let mut can_fill = vec![CellValue::none(); n];
+        for (j, &(len, color)) in self.hints.iter().enumerate() {
+            if len == 0 || len > n {
+                continue;
+            }
+            let max_start = n - len;
+            let starts: Vec<bool> = (0..=max_start)
+                .map(|p| {
+                    fwd[p][j]
+                        && run_fits(p, len, color)
+                        && if p + len == n {
+                            j + 1 == k
+                        } else if needs_gap(j, color) {
+                            self.cells[p + len].can_be(BACKGROUND) && bwd[p + len + 1][j + 1]
+                        } else {
+                            bwd[p + len][j + 1]
+                        }
+                })
+                .collect();
+            let mut prefix = vec![0u32; starts.len() + 1];
+            for (p, &ok) in starts.iter().enumerate() {
+                prefix[p + 1] = prefix[p] + ok as u32;
+            }
+            for (idx, cell) in can_fill.iter_mut().enumerate() {
+                let lo = idx.saturating_sub(len - 1);
+                let hi = idx.min(max_start);
+                if lo <= hi && prefix[hi + 1] - prefix[lo] > 0 {
+                    *cell = cell.add_color(color);
+                }
+            }
         }
+
         let mut result = Vec::new();
-        'idxs: for idx in 0..self.cells.len() {
-            if self.cells[idx] != Unknown {
+        for idx in 0..n {
+            let current = self.cells[idx];
+            if current.is_known() {
                 continue;
             }
-
-            for &val in KNOWN.iter() {
-                self.cells.to_mut()[idx] = val;
-                if !self.verify(match val {
-                    Filled => Some(opt_max(last_filled, idx)),
-                    _ => last_filled,
-                }) {
-                    let new_val = val.invert();
-                    self.cells.to_mut()[idx] = new_val;
-                    result.push(Assumption { coords: self.get_coords(idx), val: new_val });
-                    if new_val == Filled {
-                        last_filled = Some(opt_max(last_filled, idx));
+            let possible = if can_empty[idx] { can_fill[idx].add_color(BACKGROUND) } else { can_fill[idx] };
+            let narrowed = current.intersect(possible);
+            if narrowed.is_contradiction() {
+                unreachable!("do_solve already verified the line is feasible");
+            }
+            if narrowed == current {
+                continue;
+            }
+            if let Some(color) = narrowed.color() {
+                result.push(Assumption::Is { coords: self.get_coords(idx), color });
+            } else {
+                for color in current.colors() {
+                    if !narrowed.can_be(color) {
+                        result.push(Assumption::IsNot { coords: self.get_coords(idx), color });
                     }
-                    continue 'idxs;
                 }
             }
-
-            self.cells.to_mut()[idx] = Unknown;
+            self.cells.to_mut()[idx] = narrowed;
         }
-        debug_assert!(self.verify(last_filled));
         Some(result)
     }
 
     /// Solves the line to the extent currently possbile.
     ///
     /// Returns updates as a list of Assumption if the line wasn't controversial, None otherwise.
-    pub fn solve<S>(&mut self, cache: &LineCache<S>) -> LineSolution
+    /// That list is the `solution` field of the returned `LineSolveResult`.
+    pub fn solve<S>(&mut self, cache: &LineCache<S>) -> LineSolveResult
     where
         S: BuildHasher,
     {
-        let entry = cache.borrow().get(self.cells.as_ref()).map(|x| x.clone());
-        match entry {
-            Some(result) => result.clone(),
+        let n = self.cells.len();
+        let known_before = self.cells.iter().filter(|cell| cell.is_known()).count();
+        let key = PackedLine::pack(&self.cells, self.palette_size);
+        let entry = cache.borrow().get(&key).cloned();
+        let solution = match entry {
+            Some(result) => result,
             None => {
-                let key = Vec::from(self.cells.as_ref());
                 let result = self.do_solve();
                 cache.borrow_mut().entry(key).or_insert(Rc::new(result)).clone()
             }
-        }
+        };
+        let resolved = solution
+            .as_ref()
+            .as_ref()
+            .map_or(0, |changes| changes.iter().filter(|ass| matches!(ass, Assumption::Is { .. })).count());
+        let rate = if n == 0 { 1.0 } else { (known_before + resolved) as f64 / n as f64 };
+        LineSolveResult { solution, resolved, rate }
     }
 }
-
-fn opt_max<T: Ord + Copy>(a: Option<T>, b: T) -> T {
-    a.map_or(b, |v| v.max(b))
-}
@@ -1,5 +1,5 @@
 use clap::Parser;
-use nonogram::{Algorithm, SolutionResult, Solver};
+use nonogram::{Algorithm, Format, LineOrder, SolutionResult, Solver};
 use std::io;
 use std::time::Instant;
 use SolutionResult::*;
@@ -15,26 +15,50 @@ struct Cli {
     max_depth: usize,
     #[arg(short, long)]
     find_all: bool,
+    #[arg(value_enum, long, default_value_t = Format::Json, help("Input puzzle format"))]
+    format: Format,
+    #[arg(long, help("File to write the solved grid to, in --out-format"))]
+    out: Option<String>,
+    #[arg(value_enum, long, default_value_t = Format::Pbm, help("Format for --out"))]
+    out_format: Format,
+    #[arg(value_enum, long, default_value_t = LineOrder::RoundRobin, help("Line-sweep scheduling policy"))]
+    line_order: LineOrder,
 }
 
 fn main() {
     let cli = Cli::parse();
     let max_depth = if cli.max_depth > 0 { Some(cli.max_depth) } else { None };
     let solver = match cli.fname {
-        Some(fname) => Solver::from_reader(
+        Some(fname) => Solver::from_reader_with(
+            cli.format,
             std::fs::File::open(fname).unwrap(),
             max_depth,
             cli.find_all,
             cli.algorithm,
         )
         .unwrap(),
-        None => Solver::from_reader(io::stdin(), max_depth, cli.find_all, cli.algorithm).expect("Malformed input"),
-    };
+        None => Solver::from_reader_with(cli.format, io::stdin(), max_depth, cli.find_all, cli.algorithm)
+            .expect("Malformed input"),
+    }
+    .with_line_order(cli.line_order);
     let start = Instant::now();
-    match solver.solve() {
+    let result = solver.solve_with_progress(|_, rate| eprintln!("progress: {:.0}%", rate * 100.0));
+    let consensus = result.consensus();
+    match result {
         Solved(fields) => {
-            for fld in fields {
+            println!("{} solution(s) found", fields.len());
+            if let Some(consensus) = consensus {
+                println!(
+                    "Consensus skeleton ({:.1}% ambiguous):\n{}",
+                    (1.0 - consensus.solution_rate()) * 100.0,
+                    consensus
+                );
+            }
+            for (_, fld) in fields {
                 println!("{}\n", fld.to_string());
+                if let Some(out) = &cli.out {
+                    fld.write_as(cli.out_format, std::fs::File::create(out).unwrap()).unwrap();
+                }
             }
         }
         Unsolved(changes) => {
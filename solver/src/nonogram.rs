@@ -1,21 +1,26 @@
 use ahash::AHasher;
 use assumption::Assumption;
 use clap::ValueEnum;
-use common::{LineHints, Unknown, KNOWN};
-use field::Field;
+use common::{CellValue, LineHints, ColorId};
+pub use field::Field;
+pub use generator::Generator;
 use itertools::Itertools;
 use line::{Line, LineCache, LineType};
 use reachability_graph::ReachabilityGraph;
 use std::borrow::Cow;
-use std::collections::{HashMap, HashSet};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
 use std::hash::BuildHasherDefault;
 use std::io;
+use std::io::Read;
 use std::sync::{Arc, RwLock};
 use LineType::*;
 
 mod assumption;
+mod bitset;
 mod common;
 mod field;
+mod generator;
 mod line;
 mod reachability_graph;
 
@@ -35,14 +40,173 @@ pub enum Algorithm {
     TwoSat,
 }
 
+/// Which order `do_solve_by_lines` visits the lines in a sweep.
+/// `RoundRobin` keeps the order the sweep hands over: rows 0, 1, 2, ... on
+/// the first pass, then each later pass's changed lines in the iteration
+/// order of their `HashSet`, which is not sorted by index. `BySolutionRate` instead prioritizes lines that
+/// are already closest to fully known, on the theory that those are both
+/// cheapest to finish solving and most likely to hand a freshly-determined
+/// cell to their crossing lines, rather than spending the sweep on long
+/// lines that are still mostly open.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineOrder {
+    RoundRobin,
+    BySolutionRate,
+}
+
+/// How hard a puzzle is to solve, graded by the weakest technique that
+/// cracks it: pure line propagation, one round of pairwise (2-SAT-style)
+/// reasoning, or guess-and-recurse down to some depth.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Difficulty {
+    Trivial,
+    Logic,
+    Hard(usize),
+}
+
+/// Selects which on-disk shape `Solver::from_reader_with`/`Field::write_as`
+/// read or write. `Json` is the original `NonoDescription` shape; `Non` is
+/// the widely-used Olšák `.non` text format; `Pbm` is a plain-bitmap export
+/// of a solved grid and isn't a readable puzzle format.
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum Format {
+    Json,
+    Non,
+    Pbm,
+}
+
+/// An error reading a puzzle in one of the `Format`s.
+#[derive(Debug)]
+pub enum ParseError {
+    Json(serde_json::Error),
+    Non(String),
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::Json(e) => write!(f, "invalid JSON: {e}"),
+            ParseError::Non(msg) => write!(f, "invalid .non input: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<serde_json::Error> for ParseError {
+    fn from(e: serde_json::Error) -> Self {
+        ParseError::Json(e)
+    }
+}
+
+/// Parses the Olšák `.non` text format: `width`/`height`/`colors` keyword
+/// lines are accepted but not required (hint-line counts already imply the
+/// grid size), `rows`/`columns` switch which section follows, `#` starts a
+/// comment, and each hint line is a comma-separated list of run lengths.
+/// Per-run colors aren't part of this reader; every run is the sole
+/// foreground color, matching classic black-and-white `.non` puzzles.
+fn parse_non(text: &str) -> Result<(Vec<LineHints>, Vec<LineHints>), ParseError> {
+    enum Section {
+        None,
+        Rows,
+        Columns,
+    }
+    let mut section = Section::None;
+    let mut row_hints = Vec::new();
+    let mut col_hints = Vec::new();
+    for raw_line in text.lines() {
+        let line = raw_line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        match line.to_ascii_lowercase().as_str() {
+            "rows" => {
+                section = Section::Rows;
+                continue;
+            }
+            "columns" => {
+                section = Section::Columns;
+                continue;
+            }
+            _ => (),
+        }
+        let lower = line.to_ascii_lowercase();
+        if lower.starts_with("width") || lower.starts_with("height") || lower.starts_with("color") {
+            continue;
+        }
+        // `non_hints_line` writes a fully-background line as the single
+        // token "0" rather than an empty string; read it back as no runs at
+        // all instead of one zero-length run.
+        let hints: LineHints = if line == "0" {
+            Vec::new()
+        } else {
+            line.split(',')
+                .map(str::trim)
+                .filter(|tok| !tok.is_empty())
+                .map(|tok| {
+                    tok.parse::<usize>()
+                        .map(|len| (len, 1))
+                        .map_err(|e| ParseError::Non(format!("bad run length {tok:?}: {e}")))
+                })
+                .collect::<Result<_, _>>()?
+        };
+        match section {
+            Section::Rows => row_hints.push(hints),
+            Section::Columns => col_hints.push(hints),
+            Section::None => return Err(ParseError::Non(format!("hint line before 'rows'/'columns': {line:?}"))),
+        }
+    }
+    if row_hints.is_empty() || col_hints.is_empty() {
+        return Err(ParseError::Non("missing rows or columns section".to_string()));
+    }
+    Ok((row_hints, col_hints))
+}
+
+impl SolutionResult {
+    /// When `find_all` surfaced more than one candidate solution, folds them
+    /// all into a single consensus `Field` via `Field::merge`: cells every
+    /// solution agrees on are forced, cells that differ are left as an
+    /// ambiguity marker. Returns `None` for `Unsolved`/`Controversial`, or if
+    /// `Solved` somehow carries zero solutions.
+    pub fn consensus(&self) -> Option<Field> {
+        match self {
+            Solved(solutions) => Field::merge(solutions.values()),
+            Unsolved(_) | Controversial => None,
+        }
+    }
+}
+
 pub use SolutionResult::*;
 
 type ABuildHasher = BuildHasherDefault<AHasher>;
 
+/// A hint entry as it appears in JSON: either a bare run length (a legacy
+/// two-color puzzle, implicitly the sole foreground color) or a
+/// `[length, color]` pair for a colored puzzle.
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum HintEntry {
+    Plain(usize),
+    Colored(usize, ColorId),
+}
+
+impl From<HintEntry> for (usize, ColorId) {
+    fn from(entry: HintEntry) -> Self {
+        match entry {
+            HintEntry::Plain(len) => (len, 1),
+            HintEntry::Colored(len, color) => (len, color),
+        }
+    }
+}
+
 #[derive(serde::Deserialize)]
 struct NonoDescription {
-    row_hints: Vec<LineHints>,
-    col_hints: Vec<LineHints>,
+    row_hints: Vec<Vec<HintEntry>>,
+    col_hints: Vec<Vec<HintEntry>>,
+    /// Names of the puzzle's colors, 1-indexed (color 0 is always the
+    /// background). Absent or empty means a classic two-color puzzle.
+    #[serde(default)]
+    palette: Vec<String>,
 }
 
 pub struct Solver {
@@ -53,6 +217,8 @@ pub struct Solver {
     max_depth: usize,
     find_all: bool,
     algorithm: Algorithm,
+    palette_size: u32,
+    line_order: LineOrder,
 }
 
 impl Solver {
@@ -63,21 +229,48 @@ impl Solver {
         algorithm: Algorithm,
     ) -> Result<Self, serde_json::Error> {
         let descr: NonoDescription = serde_json::from_reader(rdr)?;
+        let palette_size = descr.palette.len().max(1) as u32;
+        let to_hints = |lines: Vec<Vec<HintEntry>>| -> Vec<LineHints> {
+            lines.into_iter().map(|line| line.into_iter().map(Into::into).collect()).collect()
+        };
         Ok(Self::from_hints(
-            descr.row_hints,
-            descr.col_hints,
+            to_hints(descr.row_hints),
+            to_hints(descr.col_hints),
             max_depth,
             find_all,
             algorithm,
+            palette_size,
         ))
     }
 
+    /// Like `from_reader`, but selects the on-disk `Format` to parse; `Pbm`
+    /// is write-only and is rejected here.
+    pub fn from_reader_with<R: io::Read>(
+        format: Format,
+        mut rdr: R,
+        max_depth: usize,
+        find_all: bool,
+        algorithm: Algorithm,
+    ) -> Result<Self, ParseError> {
+        match format {
+            Format::Json => Ok(Self::from_reader(rdr, max_depth, find_all, algorithm)?),
+            Format::Non => {
+                let mut text = String::new();
+                rdr.read_to_string(&mut text).map_err(|e| ParseError::Non(e.to_string()))?;
+                let (row_hints, col_hints) = parse_non(&text)?;
+                Ok(Self::from_hints(row_hints, col_hints, max_depth, find_all, algorithm, 1))
+            }
+            Format::Pbm => Err(ParseError::Non("PBM is not a readable puzzle format".to_string())),
+        }
+    }
+
     fn from_hints(
         row_hints: Vec<LineHints>,
         col_hints: Vec<LineHints>,
         max_depth: usize,
         find_all: bool,
         algorithm: Algorithm,
+        palette_size: u32,
     ) -> Self {
         let row_cache = (0..row_hints.len())
             .map(|_| Arc::new(RwLock::new(HashMap::default())))
@@ -85,11 +278,27 @@ impl Solver {
         let col_cache = (0..col_hints.len())
             .map(|_| Arc::new(RwLock::new(HashMap::default())))
             .collect();
-        Self { row_hints, col_hints, row_cache, col_cache, max_depth, find_all, algorithm }
+        Self {
+            row_hints,
+            col_hints,
+            row_cache,
+            col_cache,
+            max_depth,
+            find_all,
+            algorithm,
+            palette_size,
+            line_order: LineOrder::RoundRobin,
+        }
+    }
+
+    /// Overrides the default round-robin line-sweep order.
+    pub fn with_line_order(mut self, line_order: LineOrder) -> Self {
+        self.line_order = line_order;
+        self
     }
 
     pub fn create_field(&self) -> Field {
-        Field::new(self.nrows(), self.ncols())
+        Field::new(self.nrows(), self.ncols(), self.palette_size)
     }
 
     fn nrows(&self) -> usize {
@@ -101,11 +310,11 @@ impl Solver {
     }
 
     fn row_line<'a>(&'a self, field: &'a Field, row_idx: usize) -> Line {
-        Line::new(Row, row_idx, &self.row_hints[row_idx], field.row(row_idx))
+        Line::new(Row, row_idx, &self.row_hints[row_idx], &field.row(row_idx), self.palette_size)
     }
 
     fn col_line<'a>(&'a self, field: &'a Field, col_idx: usize) -> Line {
-        Line::new(Col, col_idx, &self.col_hints[col_idx], field.col(col_idx))
+        Line::new(Col, col_idx, &self.col_hints[col_idx], &field.col(col_idx), self.palette_size)
     }
 
     fn line<'a>(&'a self, field: &'a Field, line_type: LineType, line_idx: usize) -> Line {
@@ -122,6 +331,40 @@ impl Solver {
         }
     }
 
+    /// Orders a sweep's line indices per `self.line_order`: `RoundRobin`
+    /// leaves them as given, `BySolutionRate` visits the lines with the most
+    /// already-known cells first, via a max-heap keyed on that count (ties
+    /// broken by index, for determinism).
+    fn order_line_idxs(
+        &self,
+        field: &Field,
+        line_type: LineType,
+        line_idxs: impl Iterator<Item = usize>,
+    ) -> Vec<usize> {
+        match self.line_order {
+            LineOrder::RoundRobin => line_idxs.collect(),
+            LineOrder::BySolutionRate => {
+                let mut heap: BinaryHeap<(usize, Reverse<usize>)> = line_idxs
+                    .map(|idx| {
+                        let known = match line_type {
+                            Row => field.row(idx),
+                            Col => field.col(idx),
+                        }
+                        .iter()
+                        .filter(|cell| cell.is_known())
+                        .count();
+                        (known, Reverse(idx))
+                    })
+                    .collect();
+                let mut ordered = Vec::with_capacity(heap.len());
+                while let Some((_, Reverse(idx))) = heap.pop() {
+                    ordered.push(idx);
+                }
+                ordered
+            }
+        }
+    }
+
     fn do_solve_by_lines_step(
         &self,
         field: &mut Cow<Field>,
@@ -129,7 +372,7 @@ impl Solver {
         line_idxs: impl Iterator<Item = usize>,
     ) -> Option<Vec<Assumption>> {
         let mut all_changes: Vec<Assumption> = Vec::new();
-        for line_idx in line_idxs {
+        for line_idx in self.order_line_idxs(&field, line_type, line_idxs) {
             let mut line = self.line(&field, line_type, line_idx);
             match line.solve(self.cache(line_type, line_idx)).as_ref() {
                 Some(changes) if !changes.is_empty() => {
@@ -174,9 +417,10 @@ impl Solver {
         (0..self.nrows()).cartesian_product(0..self.ncols())
     }
 
-    fn iter_assumptions(&self) -> impl Iterator<Item = Assumption> {
+    fn iter_assumptions(&self) -> impl Iterator<Item = Assumption> + '_ {
+        let palette_size = self.palette_size;
         self.iter_coords()
-            .flat_map(|coords| KNOWN.iter().map(move |&val| Assumption { coords, val }))
+            .flat_map(move |coords| (0..=palette_size).map(move |color| Assumption::Is { coords, color }))
     }
 
     fn do_step(&self, field: &Field, depth: usize) -> SolutionResult
@@ -194,12 +438,16 @@ impl Solver {
         let mut solutions = HashMap::new();
         let mut has_unsolved = false;
         for coords in self.iter_coords() {
-            if field.get(coords) != Unknown {
+            let candidates = field.get(coords);
+            if candidates.is_known() {
                 continue;
             }
-            let mut has_controversy = false;
-            for val in KNOWN {
-                let ass = Assumption { coords, val };
+            let mut impossible = CellValue::none();
+            for color in 0..=self.palette_size {
+                if !candidates.can_be(color) {
+                    continue;
+                }
+                let ass = Assumption::Is { coords, color };
                 ass.apply(&mut field);
                 match self.do_solve(&field, max_depth) {
                     Solved(res) => {
@@ -207,19 +455,32 @@ impl Solver {
                         if !self.find_all {
                             return Solved(solutions);
                         }
-                        ass.unapply(&mut field);
+                        ass.unapply(&mut field, candidates);
                     }
                     Unsolved(_) => {
                         has_unsolved = true;
-                        ass.unapply(&mut field);
+                        ass.unapply(&mut field, candidates);
                     }
                     Controversial => {
-                        if has_controversy {
-                            return Controversial;
-                        }
-                        ass.invert().apply(&mut field);
-                        all_changes.push(ass.invert());
-                        has_controversy = true;
+                        impossible = impossible.union(CellValue::single(color));
+                        ass.unapply(&mut field, candidates);
+                    }
+                }
+            }
+            let narrowed = candidates.without_all(impossible);
+            if narrowed.is_contradiction() {
+                return Controversial;
+            }
+            if let Some(color) = narrowed.color() {
+                let ass = Assumption::Is { coords, color };
+                ass.apply(&mut field);
+                all_changes.push(ass);
+            } else {
+                for color in 0..=self.palette_size {
+                    if candidates.can_be(color) && !narrowed.can_be(color) {
+                        let ass = Assumption::IsNot { coords, color };
+                        ass.apply(&mut field);
+                        all_changes.push(ass);
                     }
                 }
             }
@@ -235,14 +496,120 @@ impl Solver {
         let mut field = field.clone();
         let mut all_changes = Vec::new();
         for ass in reach.get_impossible() {
-            let old_val = field.get(ass.coords);
-            if old_val == Unknown {
-                let ass_inv = ass.invert();
-                ass_inv.apply(&mut field);
+            let coords = ass.coords();
+            let cur = field.get(coords);
+            let ass_inv = ass.invert();
+            let narrowed = match ass_inv {
+                Assumption::Is { color, .. } => CellValue::single(color),
+                Assumption::IsNot { color, .. } => cur.without(color),
+            }
+            .intersect(cur);
+            if narrowed.is_contradiction() {
+                return Controversial;
+            }
+            if narrowed != cur {
+                field.set(coords, narrowed);
                 all_changes.push(ass_inv);
-            } else if old_val == ass.val {
+            }
+        }
+        Unsolved(all_changes)
+    }
+
+    /// Probes every still-open cell's candidate colors one at a time: commit
+    /// to the color, run line propagation to a fixpoint, and record each
+    /// forced consequence as an edge `(cell=color) -> consequence` in a
+    /// `ReachabilityGraph`. This is cheaper than `do_2sat_step`'s pairwise
+    /// probing (it never commits a second assumption before re-solving), but
+    /// still recovers two kinds of deduction from the
+    /// `ReachabilityGraph` machinery: a literal that reaches its own negation
+    /// (or, transitively, another literal at the same cell) is itself
+    /// impossible, surfaced via `get_impossible`/`apply_impossible_matches`;
+    /// and a consequence common to *every* remaining candidate of a cell is
+    /// forced regardless of which candidate turns out true, found by
+    /// intersecting each candidate's consequence set.
+    fn do_implication_step(&self, field: &Field) -> SolutionResult {
+        let mut probe_field = field.clone();
+        let mut reach = ReachabilityGraph::new();
+        let mut forced: Vec<Assumption> = Vec::new();
+
+        for coords in self.iter_coords() {
+            let candidates = field.get(coords);
+            if candidates.is_known() {
+                continue;
+            }
+            let mut common: Option<HashSet<Assumption>> = None;
+            for color in 0..=self.palette_size {
+                if !candidates.can_be(color) {
+                    continue;
+                }
+                let ass = Assumption::Is { coords, color };
+                ass.apply(&mut probe_field);
+                match self.do_solve_by_lines(&probe_field) {
+                    Controversial => reach.set_reachable(&ass, &ass.invert()),
+                    Solved(multi) => {
+                        let mut this_branch: Option<HashSet<Assumption>> = None;
+                        for solution in multi.values() {
+                            let forced: HashSet<Assumption> = self
+                                .iter_coords()
+                                .filter(|&c| c != coords && !field.get(c).is_known())
+                                .map(|c| Assumption::Is {
+                                    coords: c,
+                                    color: solution
+                                        .get(c)
+                                        .color()
+                                        .expect("a solved field has every cell known"),
+                                })
+                                .collect();
+                            this_branch = Some(match this_branch {
+                                None => forced,
+                                Some(prev) => &prev & &forced,
+                            });
+                        }
+                        let this_branch = this_branch.unwrap_or_default();
+                        for change in &this_branch {
+                            reach.set_reachable(&ass, change);
+                        }
+                        common = Some(match common {
+                            None => this_branch,
+                            Some(prev) => &prev & &this_branch,
+                        });
+                    }
+                    Unsolved(changes) => {
+                        for change in &changes {
+                            reach.set_reachable(&ass, change);
+                        }
+                        let this_branch: HashSet<Assumption> = changes.into_iter().collect();
+                        common = Some(match common {
+                            None => this_branch,
+                            Some(prev) => &prev & &this_branch,
+                        });
+                    }
+                }
+                ass.unapply(&mut probe_field, candidates);
+            }
+            if let Some(common) = common {
+                forced.extend(common);
+            }
+        }
+
+        let result = self.apply_impossible_matches(field, &reach);
+        let Unsolved(mut all_changes) = result else { return result };
+        let mut merged_field = field.clone();
+        all_changes.iter().for_each(|ass| ass.apply(&mut merged_field));
+        for ass in forced {
+            let coords = ass.coords();
+            let cur = merged_field.get(coords);
+            let narrowed = match ass {
+                Assumption::Is { color, .. } => cur.intersect(CellValue::single(color)),
+                Assumption::IsNot { color, .. } => cur.without(color),
+            };
+            if narrowed.is_contradiction() {
                 return Controversial;
             }
+            if narrowed != cur {
+                merged_field.set(coords, narrowed);
+                all_changes.push(ass);
+            }
         }
         Unsolved(all_changes)
     }
@@ -257,13 +624,15 @@ impl Solver {
         let mut solutions = HashMap::new();
         let mut has_unsolved = false;
         for ass1 in self.iter_assumptions() {
-            if field.get(ass1.coords) != Unknown {
+            let prior1 = field.get(ass1.coords());
+            if !prior1.can_be(assumption_color(&ass1)) {
                 continue;
             }
             ass1.apply(&mut field);
             for ass2 in self.iter_assumptions() {
-                if ass1.coords <= ass2.coords
-                    || field.get(ass2.coords) != Unknown
+                let prior2 = field.get(ass2.coords());
+                if ass1.coords() <= ass2.coords()
+                    || !prior2.can_be(assumption_color(&ass2))
                     || reach.is_reachable(&ass1, &ass2.invert())
                 {
                     continue;
@@ -282,9 +651,9 @@ impl Solver {
                         reach.set_reachable(&ass2, &ass1.invert());
                     }
                 }
-                ass2.unapply(&mut field);
+                ass2.unapply(&mut field, prior2);
             }
-            ass1.unapply(&mut field);
+            ass1.unapply(&mut field, prior1);
         }
 
         if solutions.len() > 0 && !(has_unsolved && self.find_all) {
@@ -310,13 +679,22 @@ impl Solver {
                 }
             }
 
+            match self.do_implication_step(&field) {
+                Solved(_) => unreachable!("do_implication_step never solves the puzzle"),
+                Controversial => return Controversial,
+                Unsolved(changes) => {
+                    if apply_changes(&changes, &mut field, &mut all_changes) {
+                        continue 'outer;
+                    }
+                }
+            }
+
             for depth in 0..max_depth {
                 let by_step = self.do_step(&field, depth);
                 match by_step {
                     Solved(_) | Controversial => return by_step,
                     Unsolved(changes) => {
-                        if !changes.is_empty() {
-                            apply_changes(&changes, &mut field, &mut all_changes);
+                        if apply_changes(&changes, &mut field, &mut all_changes) {
                             continue 'outer;
                         }
                     }
@@ -327,17 +705,128 @@ impl Solver {
     }
 
     pub fn solve(&self) -> SolutionResult {
+        self.solve_with_progress(|_, _| {})
+    }
+
+    /// Like `solve`, but calls `on_progress` with the field and its
+    /// `solution_rate` after every top-level line-propagation sweep that
+    /// leaves the puzzle unsolved and every top-level step that narrows it, so callers can show live progress or bail
+    /// out early once a target fill rate is reached.
+    pub fn solve_with_progress(&self, mut on_progress: impl FnMut(&Field, f64)) -> SolutionResult {
         let field = self.create_field();
         match self.algorithm {
-            Algorithm::ByLines => self.do_solve_by_lines(&field),
-            _ => self.do_solve(&field, self.max_depth),
+            Algorithm::ByLines => {
+                let result = self.do_solve_by_lines(&field);
+                if let Unsolved(ref changes) = result {
+                    let mut partial = field.clone();
+                    changes.iter().for_each(|ass| ass.apply(&mut partial));
+                    on_progress(&partial, partial.solution_rate());
+                }
+                result
+            }
+            _ => self.do_solve_with_progress(&field, self.max_depth, &mut on_progress),
+        }
+    }
+
+    fn do_solve_with_progress(
+        &self,
+        field: &Field,
+        max_depth: usize,
+        on_progress: &mut dyn FnMut(&Field, f64),
+    ) -> SolutionResult {
+        let mut field = field.clone();
+        let mut all_changes = Vec::new();
+
+        'outer: loop {
+            let by_lines = self.do_solve_by_lines(&field);
+            match by_lines {
+                Controversial | Solved(_) => return by_lines,
+                Unsolved(changes) => {
+                    apply_changes(&changes, &mut field, &mut all_changes);
+                    on_progress(&field, field.solution_rate());
+                    if max_depth == 0 {
+                        return Unsolved(all_changes);
+                    }
+                }
+            }
+
+            match self.do_implication_step(&field) {
+                Solved(_) => unreachable!("do_implication_step never solves the puzzle"),
+                Controversial => return Controversial,
+                Unsolved(changes) => {
+                    if apply_changes(&changes, &mut field, &mut all_changes) {
+                        on_progress(&field, field.solution_rate());
+                        continue 'outer;
+                    }
+                }
+            }
+
+            for depth in 0..max_depth {
+                let by_step = self.do_step(&field, depth);
+                match by_step {
+                    Solved(_) | Controversial => return by_step,
+                    Unsolved(changes) => {
+                        if apply_changes(&changes, &mut field, &mut all_changes) {
+                            on_progress(&field, field.solution_rate());
+                            continue 'outer;
+                        }
+                    }
+                }
+            }
+            return Unsolved(all_changes)
         }
     }
+
+    /// Grades the puzzle by the weakest technique that solves it: line
+    /// propagation alone, one round of pairwise reasoning (`max_depth == 1`),
+    /// or recursion down to `max_depth == d + 1` (reported as `Hard(d)`).
+    /// Searches up to `self.max_depth`, returning `None` if that isn't
+    /// enough to solve it.
+    ///
+    /// `Algorithm::ByLines` never recurses (see `do_step`), so it can only
+    /// ever report `Trivial` here: once line propagation alone is
+    /// insufficient, grading gives up rather than recursing with a technique
+    /// the solver wasn't configured to use.
+    pub fn grade(&self) -> Option<Difficulty> {
+        let field = self.create_field();
+        if matches!(self.do_solve_by_lines(&field), Solved(_)) {
+            return Some(Difficulty::Trivial);
+        }
+        if matches!(self.algorithm, Algorithm::ByLines) {
+            return None;
+        }
+        for depth in 1..=self.max_depth {
+            match self.do_solve(&field, depth) {
+                Solved(_) => return Some(if depth == 1 { Difficulty::Logic } else { Difficulty::Hard(depth - 1) }),
+                Controversial => return None,
+                Unsolved(_) => continue,
+            }
+        }
+        None
+    }
 }
 
-fn apply_changes(changes: &[Assumption], field: &mut Field, all_changes: &mut Vec<Assumption>) {
-    all_changes.extend_from_slice(&changes);
-    changes.iter().for_each(|ass| ass.apply(field));
+fn assumption_color(ass: &Assumption) -> ColorId {
+    match *ass {
+        Assumption::Is { color, .. } => color,
+        Assumption::IsNot { color, .. } => color,
+    }
+}
+
+/// Applies `changes` to `field` and records them in `all_changes`. Returns
+/// whether any cell's candidate set actually narrowed: a step can hand back
+/// assumptions that are already satisfied (e.g. re-deriving a forced cell
+/// it already forced last pass), and looping on those forever without
+/// narrowing anything is how a no-op step turns into a livelock.
+fn apply_changes(changes: &[Assumption], field: &mut Field, all_changes: &mut Vec<Assumption>) -> bool {
+    all_changes.extend_from_slice(changes);
+    let mut progressed = false;
+    for ass in changes {
+        let before = field.get(ass.coords());
+        ass.apply(field);
+        progressed |= field.get(ass.coords()) != before;
+    }
+    progressed
 }
 
 fn extend_solutions_from(soluions: &mut MultiSolution, new_solutions: MultiSolution) {
@@ -365,14 +854,19 @@ mod tests {
         }
     }
 
+    fn bw(lens: Vec<usize>) -> LineHints {
+        lens.into_iter().map(|len| (len, 1)).collect()
+    }
+
     #[test]
     fn solve_by_line() {
         let solver = Solver::from_hints(
-            vec![vec![5], vec![1], vec![5], vec![1], vec![5]],
-            vec![vec![3, 1], vec![1, 1, 1], vec![1, 1, 1], vec![1, 1, 1], vec![1, 3]],
+            vec![bw(vec![5]), bw(vec![1]), bw(vec![5]), bw(vec![1]), bw(vec![5])],
+            vec![bw(vec![3, 1]), bw(vec![1, 1, 1]), bw(vec![1, 1, 1]), bw(vec![1, 1, 1]), bw(vec![1, 3])],
             0,
             false,
             Algorithm::ByLines,
+            1,
         );
         solver.solve().assert_solved(&["\
                 #####\n\
@@ -387,7 +881,8 @@ mod tests {
     #[case(Algorithm::TwoSat)]
     #[case(Algorithm::Naive)]
     fn solve_ambiguous(#[case] algorithm: Algorithm) {
-        let solver = Solver::from_hints(vec![vec![1], vec![1]], vec![vec![1], vec![1]], 3, true, algorithm);
+        let solver =
+            Solver::from_hints(vec![bw(vec![1]), bw(vec![1])], vec![bw(vec![1]), bw(vec![1])], 3, true, algorithm, 1);
         solver.solve().assert_solved(&[
             "#.\n\
              .#\n",
@@ -396,17 +891,27 @@ mod tests {
         ]);
     }
 
+    #[test]
+    fn consensus_marks_disagreeing_cells_ambiguous() {
+        let solver =
+            Solver::from_hints(vec![bw(vec![1]), bw(vec![1])], vec![bw(vec![1]), bw(vec![1])], 3, true, Algorithm::Naive, 1);
+        let consensus = solver.solve().consensus().expect("find_all should yield a consensus field");
+        assert_eq!(consensus.solution_rate(), 0.0);
+        assert!(!consensus.get((0, 0)).is_known());
+    }
+
 
     #[rstest]
     #[case(Algorithm::TwoSat)]
     #[case(Algorithm::Naive)]
     fn solve_double_ambiguous_naive(#[case] algorithm: Algorithm) {
         let solver = Solver::from_hints(
-            vec![vec![1, 1], vec![1, 1]],
-            vec![vec![1], vec![1], vec![], vec![1], vec![1]],
+            vec![bw(vec![1, 1]), bw(vec![1, 1])],
+            vec![bw(vec![1]), bw(vec![1]), bw(vec![]), bw(vec![1]), bw(vec![1])],
             2,
             true,
-            algorithm
+            algorithm,
+            1,
         );
         solver.solve().assert_solved(&[
             "#..#.\n\
@@ -419,4 +924,19 @@ mod tests {
              #...#\n",
         ]);
     }
+
+    #[test]
+    fn parse_non_round_trips_empty_line() {
+        let (row_hints, col_hints) = parse_non(
+            "rows\n\
+             0\n\
+             1\n\
+             columns\n\
+             1\n\
+             0\n",
+        )
+        .unwrap();
+        assert_eq!(row_hints, vec![bw(vec![]), bw(vec![1])]);
+        assert_eq!(col_hints, vec![bw(vec![1]), bw(vec![])]);
+    }
 }
@@ -1,32 +1,55 @@
-use super::common::{CellValue, Unknown};
+use super::common::{CellValue, ColorId};
 use super::line::LineType;
 use super::reachability_graph::ReachabilityGraph;
 use super::Field;
 use itertools::Itertools;
 
-#[derive(Debug, Default, Hash, Eq, PartialEq, Clone)]
-pub struct Assumption {
-    pub coords: (usize, usize),
-    pub val: CellValue,
+/// A single-cell deduction: either a commitment ("this cell is exactly
+/// `color`") or an exclusion ("this cell cannot be `color`"). The two are
+/// each other's inverse, generalizing the old binary `Filled`/`Empty` flip
+/// to an arbitrary palette.
+#[derive(Debug, Hash, Eq, PartialEq, Clone, Copy)]
+pub enum Assumption {
+    Is { coords: (usize, usize), color: ColorId },
+    IsNot { coords: (usize, usize), color: ColorId },
 }
 
 impl Assumption {
+    pub fn coords(&self) -> (usize, usize) {
+        match *self {
+            Assumption::Is { coords, .. } => coords,
+            Assumption::IsNot { coords, .. } => coords,
+        }
+    }
+
     pub fn invert(&self) -> Self {
-        Self { coords: self.coords, val: self.val.invert() }
+        match *self {
+            Assumption::Is { coords, color } => Assumption::IsNot { coords, color },
+            Assumption::IsNot { coords, color } => Assumption::Is { coords, color },
+        }
     }
 
     pub fn apply(&self, field: &mut Field) {
-        field.set(self.coords, self.val);
+        match *self {
+            Assumption::Is { coords, color } => field.set(coords, CellValue::single(color)),
+            Assumption::IsNot { coords, color } => field.set(coords, field.get(coords).without(color)),
+        }
     }
 
-    pub fn unapply(&self, field: &mut Field) {
-        field.set(self.coords, Unknown);
+    /// Restores the cell to `prior`, its candidate set immediately before
+    /// `apply` was called. Resetting to `field.unknown_value()` instead would
+    /// erase any narrowing the cell already had going into the probe, and a
+    /// re-probe would just re-derive (and re-report as "new") the same
+    /// deductions forever.
+    pub fn unapply(&self, field: &mut Field, prior: CellValue) {
+        field.set(self.coords(), prior);
     }
 
     pub fn line_idx(&self, line_type: LineType) -> usize {
+        let coords = self.coords();
         match line_type {
-            LineType::Row => self.coords.0,
-            LineType::Col => self.coords.1,
+            LineType::Row => coords.0,
+            LineType::Col => coords.1,
         }
     }
 }
@@ -34,9 +57,9 @@ impl Assumption {
 impl ReachabilityGraph<Assumption> {
     pub fn is_impossible(&self, node: &Assumption) -> bool {
         let mut reachable: Vec<&Assumption> = self.get_reachable(node).unwrap().collect();
-        reachable.sort_unstable_by_key(|a| a.coords);
+        reachable.sort_unstable_by_key(|a| a.coords());
         for (a, b) in reachable.iter().tuple_windows() {
-            if a.coords == b.coords {
+            if a.coords() == b.coords() {
                 return true;
             }
         }
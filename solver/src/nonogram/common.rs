@@ -1,38 +1,121 @@
+pub type ColorId = u32;
+
+/// The background ("empty") color is always color 0.
+pub const BACKGROUND: ColorId = 0;
+
+/// A cell's set of still-possible colors, as a bitmask: bit 0 is the
+/// background color, bits `1..=palette_size` are the puzzle's colors.
+/// A solved cell has exactly one bit set; `CellValue::unknown` has every
+/// candidate bit set.
 #[derive(Debug, Hash, Eq, PartialEq, Copy, Clone)]
-pub enum CellValue {
-    Filled,
-    Empty,
-    Unknown,
-}
+pub struct CellValue(u32);
 
-pub use CellValue::*;
+/// Each hint is a run of `length` cells of `color`.
+pub type LineHints = Vec<(usize, ColorId)>;
+
+impl CellValue {
+    pub fn single(color: ColorId) -> Self {
+        Self(1 << color)
+    }
 
-pub const KNOWN: [CellValue; 2] = [Filled, Empty];
+    pub fn none() -> Self {
+        Self(0)
+    }
 
-pub type LineHints = Vec<usize>;
+    pub fn unknown(palette_size: u32) -> Self {
+        Self((1 << (palette_size + 1)) - 1)
+    }
 
-pub fn line_to_str(line: &Vec<CellValue>) -> String {
-    line.iter()
-        .map(|x| match *x {
-            Unknown => '.',
-            Filled => '*',
-            Empty => 'X',
+    pub fn is_known(&self) -> bool {
+        self.0.count_ones() == 1
+    }
+
+    pub fn is_contradiction(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn color(&self) -> Option<ColorId> {
+        self.is_known().then(|| self.0.trailing_zeros())
+    }
+
+    pub fn can_be(&self, color: ColorId) -> bool {
+        self.0 & (1 << color) != 0
+    }
+
+    /// The colors this cell could still take, least significant bit first.
+    pub fn colors(&self) -> impl Iterator<Item = ColorId> {
+        let mut bits = self.0;
+        std::iter::from_fn(move || {
+            if bits == 0 {
+                None
+            } else {
+                let color = bits.trailing_zeros();
+                bits &= bits - 1;
+                Some(color)
+            }
         })
-        .collect()
+    }
+
+    pub fn intersect(&self, other: CellValue) -> CellValue {
+        CellValue(self.0 & other.0)
+    }
+
+    pub fn union(&self, other: CellValue) -> CellValue {
+        CellValue(self.0 | other.0)
+    }
+
+    pub fn without(&self, color: ColorId) -> CellValue {
+        CellValue(self.0 & !(1 << color))
+    }
+
+    pub fn without_all(&self, other: CellValue) -> CellValue {
+        CellValue(self.0 & !other.0)
+    }
+
+    pub fn raw(&self) -> u32 {
+        self.0
+    }
+
+    pub fn from_raw(mask: u32) -> Self {
+        Self(mask)
+    }
 }
 
-impl CellValue {
-    pub fn invert(&self) -> Self {
-        match self {
-            Filled => Empty,
-            Empty => Filled,
-            _ => panic!("Cannot invert {self:?}"),
+/// Collapses a line of per-cell colors into hint runs: consecutive cells of
+/// the same color merge into a single run, since that's indistinguishable
+/// from one longer run once solved.
+pub fn hints_from_colors(colors: impl Iterator<Item = ColorId>) -> LineHints {
+    let mut hints = Vec::new();
+    let mut run: Option<(usize, ColorId)> = None;
+    for color in colors {
+        if color == BACKGROUND {
+            if let Some(r) = run.take() {
+                hints.push(r);
+            }
+            continue;
         }
+        match run {
+            Some((len, run_color)) if run_color == color => run = Some((len + 1, run_color)),
+            Some(r) => {
+                hints.push(r);
+                run = Some((1, color));
+            }
+            None => run = Some((1, color)),
+        }
+    }
+    if let Some(r) = run {
+        hints.push(r);
     }
+    hints
 }
 
-impl Default for CellValue {
-    fn default() -> Self {
-        Unknown
-    }
+pub fn line_to_str(line: &[CellValue]) -> String {
+    line.iter()
+        .map(|x| match x.color() {
+            Some(BACKGROUND) => '.',
+            Some(1) => '#',
+            Some(c) => std::char::from_digit(c, 36).unwrap_or('?'),
+            None => '~',
+        })
+        .collect()
 }
@@ -1,54 +1,198 @@
-use super::common::{line_to_str, CellValue, Unknown};
+use super::bitset::BitLine;
+use super::common::{hints_from_colors, line_to_str, CellValue, BACKGROUND};
 use super::line::line_cache_key;
+use super::Format;
 use std::fmt::Display;
+use std::io;
 
+/// Backing storage for a field's cells. Rather than a `CellValue` per cell,
+/// each color gets its own word-packed bitset per row and per column:
+/// `row_planes[color][row]` is "can this row's cells be `color`", so a cell's
+/// candidate mask is reassembled by reading the matching bit out of every
+/// plane. This keeps both memory and the bulk checks below (`is_solved`,
+/// `solution_rate`) at a few words per line instead of a cell-at-a-time scan.
 #[derive(Clone, Eq, PartialEq, Hash, Debug)]
 pub struct Field {
-    rows: Vec<Vec<CellValue>>,
-    cols: Vec<Vec<CellValue>>,
+    palette_size: u32,
+    nrows: usize,
+    ncols: usize,
+    row_planes: Vec<Vec<BitLine>>,
+    col_planes: Vec<Vec<BitLine>>,
 }
 
 impl Display for Field {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for row in self.rows.iter() {
-            writeln!(f, "{}", line_to_str(row))?;
+        for row_idx in 0..self.nrows {
+            writeln!(f, "{}", line_to_str(&self.row(row_idx)))?;
         }
         Ok(())
     }
 }
 
 impl Field {
-    pub fn new(nrows: usize, ncols: usize) -> Self {
+    pub fn new(nrows: usize, ncols: usize, palette_size: u32) -> Self {
+        let nplanes = palette_size as usize + 1;
         Self {
-            rows: (0..nrows).map(|_| vec![Unknown; ncols]).collect(),
-            cols: (0..ncols).map(|_| vec![Unknown; nrows]).collect(),
+            palette_size,
+            nrows,
+            ncols,
+            row_planes: (0..nplanes).map(|_| (0..nrows).map(|_| BitLine::new(ncols, true)).collect()).collect(),
+            col_planes: (0..nplanes).map(|_| (0..ncols).map(|_| BitLine::new(nrows, true)).collect()).collect(),
         }
     }
 
+    pub fn palette_size(&self) -> u32 {
+        self.palette_size
+    }
+
+    pub fn unknown_value(&self) -> CellValue {
+        CellValue::unknown(self.palette_size)
+    }
+
+    /// For a row, the bitsets of columns seen in at least one color plane and
+    /// of columns seen in at least two: a column is known iff it's in the
+    /// first but not the second, and contradictory iff it's in neither.
+    fn row_seen_masks(&self, row_idx: usize) -> (BitLine, BitLine) {
+        let mut seen_one = BitLine::new(self.ncols, false);
+        let mut seen_two = BitLine::new(self.ncols, false);
+        for color_planes in self.row_planes.iter() {
+            let plane = &color_planes[row_idx];
+            let mut overlap = seen_one.clone();
+            overlap.intersect_with(plane);
+            seen_two.union_with(&overlap);
+            seen_one.union_with(plane);
+        }
+        (seen_one, seen_two)
+    }
+
+    /// A row is fully solved once every cell has exactly one possible color:
+    /// tracked word-at-a-time via a "seen at least once"/"seen at least
+    /// twice" pair of bitsets accumulated over the color planes.
     pub fn is_solved(&self) -> bool {
-        self.rows.iter().all(|row| row.iter().all(|&x| x != Unknown))
+        (0..self.nrows).all(|row_idx| {
+            let (seen_one, seen_two) = self.row_seen_masks(row_idx);
+            seen_two.is_all_clear() && seen_one.count_ones() == self.ncols
+        })
     }
 
-    pub fn row(&self, idx: usize) -> &[CellValue] {
-        &self.rows[idx]
+    /// Fraction of cells that are already known (exactly one candidate
+    /// color), for progress reporting.
+    pub fn solution_rate(&self) -> f64 {
+        let total = self.nrows * self.ncols;
+        if total == 0 {
+            return 1.0;
+        }
+        let known: usize = (0..self.nrows)
+            .map(|row_idx| {
+                let (seen_one, seen_two) = self.row_seen_masks(row_idx);
+                seen_one.count_ones() - seen_two.count_ones()
+            })
+            .sum();
+        known as f64 / total as f64
     }
 
-    pub fn col(&self, idx: usize) -> &[CellValue] {
-        &self.cols[idx]
+    pub fn row(&self, idx: usize) -> Vec<CellValue> {
+        (0..self.ncols)
+            .map(|col_idx| self.get((idx, col_idx)))
+            .collect()
+    }
+
+    pub fn col(&self, idx: usize) -> Vec<CellValue> {
+        (0..self.nrows)
+            .map(|row_idx| self.get((row_idx, idx)))
+            .collect()
     }
 
     pub fn get(&self, coords: (usize, usize)) -> CellValue {
         let (row_idx, col_idx) = coords;
-        self.rows[row_idx][col_idx]
+        let mut mask = 0u32;
+        for (color, plane) in self.row_planes.iter().enumerate() {
+            if plane[row_idx].get(col_idx) {
+                mask |= 1 << color;
+            }
+        }
+        CellValue::from_raw(mask)
     }
 
     pub fn set(&mut self, coords: (usize, usize), val: CellValue) {
         let (row_idx, col_idx) = coords;
-        self.rows[row_idx][col_idx] = val;
-        self.cols[col_idx][row_idx] = val;
+        for color in 0..=self.palette_size {
+            let can_be = val.can_be(color);
+            self.row_planes[color as usize][row_idx].set(col_idx, can_be);
+            self.col_planes[color as usize][col_idx].set(row_idx, can_be);
+        }
     }
 
     pub fn key(&self) -> Vec<u8> {
-        self.rows.iter().flat_map(|row| line_cache_key(&row)).collect()
+        (0..self.nrows).flat_map(|idx| line_cache_key(&self.row(idx), self.palette_size)).collect()
+    }
+
+    /// Folds a set of solved fields into one consensus "skeleton": a cell
+    /// keeps a single color bit if every field agrees on it there, or ends up
+    /// with every color bit any field took at that cell otherwise, which is
+    /// exactly the `CellValue` ambiguity marker already doubles as. Mirrors
+    /// nonogrid's `add_color` merge across the candidate solutions of an
+    /// under-determined puzzle.
+    pub fn merge<'a>(fields: impl IntoIterator<Item = &'a Field>) -> Option<Field> {
+        let mut fields = fields.into_iter();
+        let mut merged = fields.next()?.clone();
+        for field in fields {
+            for color in 0..merged.row_planes.len() {
+                for row_idx in 0..merged.nrows {
+                    merged.row_planes[color][row_idx].union_with(&field.row_planes[color][row_idx]);
+                }
+                for col_idx in 0..merged.ncols {
+                    merged.col_planes[color][col_idx].union_with(&field.col_planes[color][col_idx]);
+                }
+            }
+        }
+        Some(merged)
+    }
+
+    /// Writes this field out as `format`: `Non` re-derives `rows`/`columns`
+    /// hints from the grid's own runs (dropping per-run colors, since
+    /// classic `.non` is monochrome), `Pbm` emits a plain-bitmap bitmap with
+    /// any non-background color rendered as a set pixel. `Json` isn't a
+    /// solution export format.
+    pub fn write_as<W: io::Write>(&self, format: Format, mut writer: W) -> io::Result<()> {
+        match format {
+            Format::Pbm => {
+                writeln!(writer, "P1")?;
+                writeln!(writer, "{} {}", self.ncols, self.nrows)?;
+                for row_idx in 0..self.nrows {
+                    let bits = self
+                        .row(row_idx)
+                        .iter()
+                        .map(|cell| if cell.color().map_or(true, |c| c == BACKGROUND) { "0" } else { "1" })
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    writeln!(writer, "{bits}")?;
+                }
+                Ok(())
+            }
+            Format::Non => {
+                writeln!(writer, "width {}", self.ncols)?;
+                writeln!(writer, "height {}", self.nrows)?;
+                writeln!(writer, "rows")?;
+                for row_idx in 0..self.nrows {
+                    writeln!(writer, "{}", non_hints_line(&self.row(row_idx)))?;
+                }
+                writeln!(writer, "columns")?;
+                for col_idx in 0..self.ncols {
+                    writeln!(writer, "{}", non_hints_line(&self.col(col_idx)))?;
+                }
+                Ok(())
+            }
+            Format::Json => Err(io::Error::new(io::ErrorKind::Unsupported, "JSON is not a solution export format")),
+        }
+    }
+}
+
+fn non_hints_line(cells: &[CellValue]) -> String {
+    let hints = hints_from_colors(cells.iter().map(|cell| cell.color().unwrap_or(BACKGROUND)));
+    if hints.is_empty() {
+        "0".to_string()
+    } else {
+        hints.iter().map(|(len, _)| len.to_string()).collect::<Vec<_>>().join(",")
     }
 }
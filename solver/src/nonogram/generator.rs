@@ -0,0 +1,110 @@
+use super::common::{hints_from_colors, ColorId, LineHints};
+use super::{Algorithm, Difficulty, Solver, SolutionResult};
+use rand::Rng;
+use SolutionResult::*;
+
+/// Produces random nonograms with a unique solution: sample a fully-colored
+/// grid, derive `row_hints`/`col_hints` straight from its runs, then discard
+/// the candidate unless solving with `find_all` confirms exactly one
+/// solution.
+pub struct Generator {
+    nrows: usize,
+    ncols: usize,
+    palette_size: u32,
+}
+
+impl Generator {
+    pub fn new(nrows: usize, ncols: usize, palette_size: u32) -> Self {
+        Self { nrows, ncols, palette_size }
+    }
+
+    fn random_grid<R: Rng>(&self, rng: &mut R) -> Vec<Vec<ColorId>> {
+        (0..self.nrows)
+            .map(|_| (0..self.ncols).map(|_| rng.gen_range(0..=self.palette_size)).collect())
+            .collect()
+    }
+
+    fn hints_from_grid(&self, grid: &[Vec<ColorId>]) -> (Vec<LineHints>, Vec<LineHints>) {
+        let row_hints = grid.iter().map(|row| hints_from_colors(row.iter().copied())).collect();
+        let col_hints = (0..self.ncols)
+            .map(|c| hints_from_colors(grid.iter().map(|row| row[c])))
+            .collect();
+        (row_hints, col_hints)
+    }
+
+    /// Samples a random grid and keeps it only if its hints pin down exactly
+    /// one solution.
+    pub fn generate<R: Rng>(&self, rng: &mut R, max_depth: usize) -> Option<(Vec<LineHints>, Vec<LineHints>)> {
+        let grid = self.random_grid(rng);
+        let (row_hints, col_hints) = self.hints_from_grid(&grid);
+        let solver = Solver::from_hints(
+            row_hints.clone(),
+            col_hints.clone(),
+            max_depth,
+            true,
+            Algorithm::TwoSat,
+            self.palette_size,
+        );
+        match solver.solve() {
+            Solved(solutions) if solutions.len() == 1 => Some((row_hints, col_hints)),
+            _ => None,
+        }
+    }
+
+    /// Keeps sampling unique puzzles until one's `grade()` satisfies `band`,
+    /// or gives up after `max_attempts`.
+    pub fn generate_with_difficulty<R: Rng>(
+        &self,
+        rng: &mut R,
+        max_depth: usize,
+        band: impl Fn(&Difficulty) -> bool,
+        max_attempts: usize,
+    ) -> Option<(Vec<LineHints>, Vec<LineHints>, Difficulty)> {
+        for _ in 0..max_attempts {
+            let Some((row_hints, col_hints)) = self.generate(rng, max_depth) else { continue };
+            let solver = Solver::from_hints(
+                row_hints.clone(),
+                col_hints.clone(),
+                max_depth,
+                false,
+                Algorithm::TwoSat,
+                self.palette_size,
+            );
+            if let Some(difficulty) = solver.grade() {
+                if band(&difficulty) {
+                    return Some((row_hints, col_hints, difficulty));
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn generate_yields_a_uniquely_solvable_puzzle() {
+        let generator = Generator::new(3, 3, 0);
+        let mut rng = StdRng::seed_from_u64(1);
+        let (row_hints, col_hints) =
+            (0..50).find_map(|_| generator.generate(&mut rng, 2)).expect("some seed should yield a unique grid");
+        let solver = Solver::from_hints(row_hints, col_hints, 2, true, Algorithm::TwoSat, 0);
+        match solver.solve() {
+            Solved(solutions) => assert_eq!(solutions.len(), 1),
+            other => panic!("expected a unique solution, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn generate_with_difficulty_respects_the_band() {
+        let generator = Generator::new(3, 3, 0);
+        let mut rng = StdRng::seed_from_u64(1);
+        let (_, _, difficulty) = generator
+            .generate_with_difficulty(&mut rng, 2, |d| *d == Difficulty::Trivial, 50)
+            .expect("some seed should yield a gradeable unique puzzle");
+        assert_eq!(difficulty, Difficulty::Trivial);
+    }
+}
@@ -1,11 +1,8 @@
 use super::assumption::Assumption;
-use super::common::{line_to_str, CellValue, LineHints};
-use crate::nonogram::common::KNOWN;
-use std::borrow::Cow;
+use super::common::{line_to_str, CellValue, LineHints, BACKGROUND};
 use std::collections::HashMap;
 use std::hash::BuildHasher;
 use std::sync::{Arc, RwLock};
-use CellValue::*;
 use LineType::*;
 
 #[cfg(test)]
@@ -35,12 +32,19 @@ pub struct Line<'a> {
     line_type: LineType,
     line_idx: usize,
     hints: &'a LineHints,
-    cells: Cow<'a, [CellValue]>,
+    cells: Vec<CellValue>,
+    palette_size: u32,
 }
 
 impl<'a> Line<'a> {
-    pub fn new(line_type: LineType, line_idx: usize, hints: &'a LineHints, cells: &'a [CellValue]) -> Self {
-        Self { line_type, line_idx, hints, cells: Cow::from(cells) }
+    pub fn new(
+        line_type: LineType,
+        line_idx: usize,
+        hints: &'a LineHints,
+        cells: &[CellValue],
+        palette_size: u32,
+    ) -> Self {
+        Self { line_type, line_idx, hints, cells: cells.to_vec(), palette_size }
     }
 
     #[allow(dead_code)]
@@ -48,41 +52,165 @@ impl<'a> Line<'a> {
         line_to_str(&self.cells)
     }
 
-    fn do_verify(&self, hint_idx: usize, cells_offset: usize) -> bool {
-        if cells_offset >= self.cells.len() {
-            return hint_idx == self.hints.len();
+    /// For each color, `reach[color][i]` is the length of the longest run of
+    /// that color starting at cell `i` (0 once a non-matching or end-of-line
+    /// cell is hit). Turns an "are cells `[s, s+len)` all compatible with
+    /// `color`" check into an O(1) lookup instead of an O(len) scan.
+    fn reach_tables(&self) -> Vec<Vec<usize>> {
+        let n = self.cells.len();
+        (0..=self.palette_size)
+            .map(|color| {
+                let mut reach = vec![0; n + 1];
+                for i in (0..n).rev() {
+                    reach[i] = if self.cells[i].can_be(color) { reach[i + 1] + 1 } else { 0 };
+                }
+                reach
+            })
+            .collect::<Vec<_>>()
+    }
+
+    /// Whether hints `a` and `b` (given as hint indices) need a mandatory
+    /// background cell between them: only when they share a color, since
+    /// differently-colored runs are distinguishable without a gap.
+    fn needs_gap_between(&self, a: usize, b: usize) -> bool {
+        self.hints[a].1 == self.hints[b].1
+    }
+
+    /// `fits_left[i][j]`: hints `0..j` can be placed within `cells[0..i]`
+    /// (with background padding allowed after the last of them).
+    fn fits_left(&self, reach: &[Vec<usize>]) -> Vec<Vec<bool>> {
+        let n = self.cells.len();
+        let k = self.hints.len();
+        let mut fits = vec![vec![false; k + 1]; n + 1];
+        fits[0][0] = true;
+        for i in 1..=n {
+            for j in 0..=k {
+                if self.cells[i - 1].can_be(BACKGROUND) && fits[i - 1][j] {
+                    fits[i][j] = true;
+                    continue;
+                }
+                if j == 0 {
+                    continue;
+                }
+                let (len, color) = self.hints[j - 1];
+                if len > i || reach[color as usize][i - len] < len {
+                    continue;
+                }
+                let start = i - len;
+                let placed = if j >= 2 && self.needs_gap_between(j - 2, j - 1) {
+                    start >= 1 && self.cells[start - 1].can_be(BACKGROUND) && fits[start - 1][j - 1]
+                } else {
+                    fits[start][j - 1]
+                };
+                fits[i][j] = placed;
+            }
         }
-        let cells = &self.cells[cells_offset..];
-        if hint_idx == self.hints.len() {
-            return cells.iter().all(|&x| x != Filled);
+        fits
+    }
+
+    /// `fits_right[i][j]`: hints `j..k` can be placed within `cells[i..n]`
+    /// (with background padding allowed before the first of them).
+    fn fits_right(&self, reach: &[Vec<usize>]) -> Vec<Vec<bool>> {
+        let n = self.cells.len();
+        let k = self.hints.len();
+        let mut fits = vec![vec![false; k + 1]; n + 1];
+        fits[n][k] = true;
+        for i in (0..n).rev() {
+            for j in (0..=k).rev() {
+                if self.cells[i].can_be(BACKGROUND) && fits[i + 1][j] {
+                    fits[i][j] = true;
+                    continue;
+                }
+                if j == k {
+                    continue;
+                }
+                let (len, color) = self.hints[j];
+                if reach[color as usize][i] < len {
+                    continue;
+                }
+                let end = i + len;
+                let placed = if j + 1 < k && self.needs_gap_between(j, j + 1) {
+                    end < n && self.cells[end].can_be(BACKGROUND) && fits[end + 1][j + 1]
+                } else {
+                    fits[end][j + 1]
+                };
+                fits[i][j] = placed;
+            }
         }
-        let current_hint = self.hints[hint_idx];
-        let size = cells.len();
+        fits
+    }
 
-        if current_hint > size {
-            return false;
+    /// Per-cell candidate colors (and background) reachable by *some*
+    /// globally-consistent placement of every hint, computed from the
+    /// forward/backward feasibility tables in O(n·k) instead of probing each
+    /// cell against a recursive verify.
+    fn feasible_cells(&self) -> Option<Vec<CellValue>> {
+        let n = self.cells.len();
+        let k = self.hints.len();
+        let reach = self.reach_tables();
+        let fits_left = self.fits_left(&reach);
+        let fits_right = self.fits_right(&reach);
+        if !fits_left[n][k] {
+            return None;
         }
-        for (start, &val) in cells[..size - current_hint + 1].iter().enumerate() {
-            let end = start + current_hint;
-            if cells[start..end].iter().all(|&x| x != Empty)
-                && (end == size || cells[end] != Filled)
-                && self.do_verify(hint_idx + 1, cells_offset + end + 1)
-            {
-                return true;
+
+        let mut feasible = vec![CellValue::none(); n];
+
+        for (j, &(len, color)) in self.hints.iter().enumerate() {
+            if len > n {
+                continue;
+            }
+            // A run start at `s` counts if it's part of some complete valid
+            // arrangement; a +1/-1 `delta` per such start turns
+            // "is any start's run covering cell p" into a running sum.
+            let mut delta = vec![0i32; n + 1];
+            for s in 0..=n - len {
+                if reach[color as usize][s] < len {
+                    continue;
+                }
+                let before_ok = if j >= 1 && self.needs_gap_between(j - 1, j) {
+                    s >= 1 && self.cells[s - 1].can_be(BACKGROUND) && fits_left[s - 1][j]
+                } else {
+                    fits_left[s][j]
+                };
+                if !before_ok {
+                    continue;
+                }
+                let end = s + len;
+                let after_ok = if j + 1 < k && self.needs_gap_between(j, j + 1) {
+                    end < n && self.cells[end].can_be(BACKGROUND) && fits_right[end + 1][j + 1]
+                } else {
+                    fits_right[end][j + 1]
+                };
+                if after_ok {
+                    delta[s] += 1;
+                    delta[end] -= 1;
+                }
             }
-            if val == Filled {
-                return false;
+            let mut running = 0;
+            for (p, cell) in feasible.iter_mut().enumerate() {
+                running += delta[p];
+                if running > 0 {
+                    *cell = cell.union(CellValue::single(color));
+                }
+            }
+        }
+
+        for (p, cell) in feasible.iter_mut().enumerate() {
+            if self.cells[p].can_be(BACKGROUND) && (0..=k).any(|j| fits_left[p][j] && fits_right[p + 1][j]) {
+                *cell = cell.union(CellValue::single(BACKGROUND));
             }
         }
-        false
+
+        Some(feasible)
     }
 
     fn verify(&self) -> bool {
-        self.do_verify(0, 0)
+        self.feasible_cells().is_some()
     }
 
     fn cache_key(&self) -> LineCacheKey {
-        line_cache_key(self.cells.as_ref())
+        line_cache_key(&self.cells, self.palette_size)
     }
 
     fn get_coords(&self, idx: usize) -> (usize, usize) {
@@ -93,26 +221,27 @@ impl<'a> Line<'a> {
     }
 
     fn do_solve(&mut self) -> Option<Vec<Assumption>> {
-        if !self.verify() {
-            return None;
-        }
+        let feasible = self.feasible_cells()?;
         let mut result = Vec::new();
-        'idxs: for idx in 0..self.cells.len() {
-            if self.cells[idx] != Unknown {
+        for (idx, &narrowed) in feasible.iter().enumerate() {
+            let candidates = self.cells[idx];
+            if candidates.is_known() {
                 continue;
             }
-
-            for &val in KNOWN.iter() {
-                self.cells.to_mut()[idx] = val;
-                if !self.verify() {
-                    let new_val = val.invert();
-                    self.cells.to_mut()[idx] = new_val;
-                    result.push(Assumption { coords: self.get_coords(idx), val: new_val });
-                    continue 'idxs;
+            let narrowed = candidates.intersect(narrowed);
+            if narrowed.is_contradiction() {
+                return None;
+            }
+            self.cells[idx] = narrowed;
+            if let Some(color) = narrowed.color() {
+                result.push(Assumption::Is { coords: self.get_coords(idx), color });
+            } else {
+                for color in candidates.colors() {
+                    if !narrowed.can_be(color) {
+                        result.push(Assumption::IsNot { coords: self.get_coords(idx), color });
+                    }
                 }
             }
-
-            self.cells.to_mut()[idx] = Unknown;
         }
         debug_assert!(self.verify());
         Some(result)
@@ -142,19 +271,28 @@ impl<'a> Line<'a> {
     }
 }
 
-pub fn line_cache_key(cells: &[CellValue]) -> LineCacheKey {
-    let mut packed_cells = vec![0u8; (cells.len() + 3) / 4];
-    let mut idx = 0;
-    for chunk in cells.chunks(4) {
-        let c = match chunk {
-            [b1, b2, b3, b4] => ((*b1 as u8) << 6) | ((*b2 as u8) << 4) | ((*b3 as u8) << 2) | (*b4 as u8),
-            [b1, b2, b3] => ((*b1 as u8) << 4) | ((*b2 as u8) << 2) | (*b3 as u8),
-            [b1, b2] => ((*b1 as u8) << 2) | (*b2 as u8),
-            [b1] => *b1 as u8,
-            _ => panic!("Impossible chunk: {chunk:?}"),
-        };
-        packed_cells[idx] = c;
-        idx += 1;
+/// Packs a line's candidate masks into bytes for use as a cache key, using
+/// exactly `palette_size + 1` bits per cell (one per candidate color,
+/// background included) rather than a full 32-bit word. The multi-color
+/// `CellValue`/`LineHints` representation this packs is the line solver's
+/// own; it was generalized in the bitset-backed `Field` rework, not here —
+/// this function only tightens how that representation gets hashed.
+pub fn line_cache_key(cells: &[CellValue], palette_size: u32) -> LineCacheKey {
+    let width = (palette_size + 1) as usize;
+    let mut packed = Vec::with_capacity((cells.len() * width + 7) / 8 + 1);
+    let mut acc: u64 = 0;
+    let mut acc_bits = 0usize;
+    for cell in cells {
+        acc |= (cell.raw() as u64) << acc_bits;
+        acc_bits += width;
+        while acc_bits >= 8 {
+            packed.push((acc & 0xff) as u8);
+            acc >>= 8;
+            acc_bits -= 8;
+        }
     }
-    packed_cells
-}
\ No newline at end of file
+    if acc_bits > 0 {
+        packed.push((acc & 0xff) as u8);
+    }
+    packed
+}